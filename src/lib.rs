@@ -5,6 +5,27 @@
 /// Activate wasm_bindgen to be able to compile to wasm.
 use wasm_bindgen::prelude::*;
 
+/// Pull in rayon's parallel iterators so `count_iterations` can spread
+/// the embarrassingly-parallel escape-time computation across workers.
+use rayon::prelude::*;
+
+/// Re-export the thread-pool initializer from wasm-bindgen-rayon.
+/// The TypeScript front end must `await` this once, before the first
+/// call to `update_image`, to spin up the worker-backed pool. It relies
+/// on SharedArrayBuffer, so the page needs cross-origin isolation
+/// headers (COOP/COEP) set.
+pub use wasm_bindgen_rayon::init_thread_pool;
+
+/// Perturbation-theory deep-zoom rendering, for zoom levels beyond what
+/// `f64` coordinates can resolve.
+mod perturbation;
+use perturbation::ReferenceOrbit;
+
+/// Encode the rendered image as PNG bytes for `Mandelbrot::to_png`.
+use image::ColorType;
+use image::codecs::png::PngEncoder;
+use image::ImageEncoder;
+
 /// Replace the default allocator with wee_alloc.
 /// This is suitable when compiling to wasm.
 #[global_allocator]
@@ -40,6 +61,71 @@ struct PointUsize {
     y: usize,
 }
 
+/// Escape radius, squared (2^16), used in place of the classic 2 so the
+/// normalized iteration count in `count_iter_for_index` has room to settle.
+const ESCAPE_RADIUS_SQ: f64 = 65536.0;
+
+/// Convert an HSV color (hue in degrees `0..360`, saturation and value
+/// in `0..1`) to 8-bit RGB.
+fn hsv_to_rgb(h: f64, s: f64, v: f64) -> [u8;3] {
+    let c = v*s;
+    let hp = h/60.0;
+    let x = c*(1.0 - (hp%2.0 - 1.0).abs());
+    let (r1,g1,b1) = match hp as u32 {
+        0 => (c,x,0.0),
+        1 => (x,c,0.0),
+        2 => (0.0,c,x),
+        3 => (0.0,x,c),
+        4 => (x,0.0,c),
+        _ => (c,0.0,x),
+    };
+    let m = v - c;
+    [
+        ((r1+m)*255.0).round() as u8,
+        ((g1+m)*255.0).round() as u8,
+        ((b1+m)*255.0).round() as u8,
+    ]
+}
+
+/// Build a full-rainbow palette by sweeping hue `0..350°` across `n_stops`.
+fn rainbow_palette(n_stops: usize) -> Vec<[u8;3]> {
+    (0..n_stops).map(|i| {
+        let hue = 350.0*i as f64/(n_stops-1).max(1) as f64;
+        hsv_to_rgb(hue, 1.0, 1.0)
+    }).collect()
+}
+
+/// Resample a small set of named RGB stops up to `n_stops`, interpolating
+/// linearly between the two nearest ones.
+fn resample_stops(stops: &[[u8;3]], n_stops: usize) -> Vec<[u8;3]> {
+    (0..n_stops).map(|i| {
+        let pos = i as f64/(n_stops-1).max(1) as f64 * (stops.len()-1) as f64;
+        let lo = pos.floor() as usize;
+        let hi = (lo+1).min(stops.len()-1);
+        let t = pos - lo as f64;
+        [
+            (stops[lo][0] as f64 + t*(stops[hi][0] as f64 - stops[lo][0] as f64)) as u8,
+            (stops[lo][1] as f64 + t*(stops[hi][1] as f64 - stops[lo][1] as f64)) as u8,
+            (stops[lo][2] as f64 + t*(stops[hi][2] as f64 - stops[lo][2] as f64)) as u8,
+        ]
+    }).collect()
+}
+
+/// Warm black -> red -> yellow -> white ramp.
+fn fire_palette(n_stops: usize) -> Vec<[u8;3]> {
+    resample_stops(&[[0,0,0], [128,0,0], [255,128,0], [255,255,0], [255,255,255]], n_stops)
+}
+
+/// Plain black -> white ramp.
+fn grayscale_palette(n_stops: usize) -> Vec<[u8;3]> {
+    resample_stops(&[[0,0,0], [255,255,255]], n_stops)
+}
+
+/// Deep blue -> cyan -> white ramp.
+fn ocean_palette(n_stops: usize) -> Vec<[u8;3]> {
+    resample_stops(&[[0,0,64], [0,64,128], [0,192,255], [255,255,255]], n_stops)
+}
+
 struct MetaData {
     /// Corner Point of image.
     z0: PointF64,
@@ -56,14 +142,9 @@ struct MetaData {
     /// Number of colors in image.
     n_colors: usize,
 
-    /// Red RGB-value.
-    red: u8,
-
-    /// Green RGB-value.
-    green: u8,
-
-    /// Blue RGB-value.
-    blue: u8,
+    /// Color palette: a sequence of RGB stops that the binned weight in
+    /// `iterations_to_color` is linearly interpolated across.
+    palette: Vec<[u8;3]>,
 
 }
 
@@ -83,23 +164,53 @@ impl MetaData {
         self.d.y = self.d.x;
     }
 
-    /// Compute the escape iteration for one point c.
-    /// 0 is returned when the maximum number of iterations are reached.
-    fn count_iter_for_index(&self, i: usize) -> usize {
+    /// Compute the escape iteration for one point c, together with its
+    /// normalized (fractional) iteration count `nu` and its exterior
+    /// distance estimate `dist`.
+    ///
+    /// The normalized count turns the coarse integer escape count into a
+    /// continuous value, which is what lets `iterations_to_color` blend
+    /// between neighboring histogram bins instead of producing hard bands.
+    /// The distance estimate instead measures, in image coordinate units,
+    /// how far this point is from the set's boundary, which is what lets
+    /// `distance_to_color` render crisp filaments regardless of zoom.
+    /// (0, 0.0, 0.0) is returned when the maximum number of iterations
+    /// are reached.
+    fn count_iter_for_index(&self, i: usize) -> (usize, f64, f64) {
         let c = self.get_coord(i);
         let mut z = PointF64{x:0.0, y:0.0};
+        // Derivative of z w.r.t. c, tracked alongside the orbit so the
+        // distance estimate can be computed on escape: dz_0 = 0.
+        let mut dz = PointF64{x:0.0, y:0.0};
         for iter in 0..self.max_iter {
-            // Check |z|^2 >= 4 for escape-iteration.
-            if z.x*z.x + z.y*z.y >= 4.0 {
-                return iter
+            let mag2 = z.x*z.x + z.y*z.y;
+            // A larger bailout radius (2^8, i.e. |z|^2 >= 2^16, instead
+            // of 2) gives the
+            // normalized iteration count below room to settle, which
+            // removes visible color bands. See "Normalized Iteration
+            // Count" on Rosetta Code.
+            if mag2 >= ESCAPE_RADIUS_SQ {
+                let mag = mag2.sqrt();
+                let nu = iter as f64 + 1.0 - (mag.ln().ln())/std::f64::consts::LN_2;
+                // Distance estimation: dist = |z|*ln(|z|)/|dz|. See
+                // Rosetta Code's "Distance Estimation" section.
+                let dz_mag = (dz.x*dz.x + dz.y*dz.y).sqrt();
+                let dist = mag*mag.ln()/dz_mag;
+                return (iter, nu, dist)
             }
+            // dz_{n+1} = 2*z_n*dz_n + 1
+            let dzx = 2.0*(z.x*dz.x - z.y*dz.y) + 1.0;
+            let dzy = 2.0*(z.x*dz.y + z.y*dz.x);
+            dz.x = dzx;
+            dz.y = dzy;
+
             // Update z <- z*z + c
             let zx = z.x*z.x - z.y*z.y + c.x;
             z.y = 2.0*z.x*z.y + c.y;
             z.x = zx;
         }
-        // Return 0 when max-iter reached.
-        0
+        // Return (0, 0.0, 0.0) when max-iter reached.
+        (0, 0.0, 0.0)
     }
     /// Get the coordinate for a multiple-index in the image.
     fn get_coord(&self, i: usize) -> PointF64 {
@@ -109,6 +220,36 @@ impl MetaData {
         }
     }
 
+    /// Map a binned weight (in `0..n_colors`, possibly fractional for
+    /// smooth coloring) onto this palette, interpolating linearly
+    /// between the two nearest palette stops.
+    fn color_at(&self, weight: f64) -> [u8;3] {
+        let n_stops = self.palette.len();
+        let span = (self.n_colors-1).max(1) as f64;
+        let pos = (weight/span) * (n_stops-1) as f64;
+        let lo = (pos.floor() as usize).min(n_stops-1);
+        let hi = (lo+1).min(n_stops-1);
+        let t = pos - lo as f64;
+        let a = self.palette[lo];
+        let b = self.palette[hi];
+        [
+            (a[0] as f64 + t*(b[0] as f64 - a[0] as f64)) as u8,
+            (a[1] as f64 + t*(b[1] as f64 - a[1] as f64)) as u8,
+            (a[2] as f64 + t*(b[2] as f64 - a[2] as f64)) as u8,
+        ]
+    }
+
+    /// Get a multiple-index's offset from the center of the image, in
+    /// image coordinate units. Unlike `get_coord`, this never adds onto
+    /// `z0`, so it stays accurate in perturbation mode even once `z0`
+    /// itself has collapsed under `f64` precision loss.
+    fn get_delta_coord(&self, i: usize) -> PointF64 {
+        PointF64 {
+            x: ((i%self.n.x) as f64 + 0.5 - self.n.x as f64/2.0) * self.d.x,
+            y: ((i/self.n.x) as f64 + 0.5 - self.n.y as f64/2.0) * self.d.y,
+        }
+    }
+
 }
 
 /// Contains all necessary info about the Mandelbrot image.
@@ -120,11 +261,30 @@ pub struct Mandelbrot {
     /// Work vector of full image size.
     work: Vec<usize>,
 
+    /// Normalized (fractional) iteration count for each pixel, parallel
+    /// to `work`, used to interpolate between histogram bins for smooth
+    /// coloring. Points that never escape keep the sentinel 0.0.
+    smooth: Vec<f64>,
+
     /// Image represented with u8.
     img: Vec<u8>,
 
     /// Mapping from escape-iteration to interpolation-weight for the color.
     iterations: Vec<usize>,
+
+    /// High-precision reference orbit for perturbation-theory deep-zoom
+    /// rendering. `None` means plain `f64` escape-time is used instead;
+    /// set it via `set_center` once the zoom exceeds what `f64` can resolve.
+    reference: Option<ReferenceOrbit>,
+
+    /// Exterior distance estimate for each pixel, parallel to `work`,
+    /// in image coordinate units. Only populated outside perturbation
+    /// mode; see `count_iter_for_index`.
+    distance: Vec<f64>,
+
+    /// When set, `iterations_to_color` renders `distance` as grayscale
+    /// instead of the usual escape-time palette, for crisp filaments.
+    distance_mode: bool,
 }
 
 #[wasm_bindgen]
@@ -155,14 +315,84 @@ impl Mandelbrot {
                 d: PointF64{x: (x1-x0) / nx as f64, y: (y1-y0) / ny as f64},
                 max_iter,
                 n_colors,
-                red,
-                green,
-                blue,
+                // A two-stop black-to-(red,green,blue) gradient reproduces
+                // the original single-hue ramp as the default palette.
+                palette: vec![[0,0,0], [red,green,blue]],
             },
             work: vec![0;nx*ny],
+            smooth: vec![0.0;nx*ny],
             img: vec![0;4*nx*ny],
             iterations: vec![0;max_iter],
+            reference: None,
+            distance: vec![0.0;nx*ny],
+            distance_mode: false,
+        }
+    }
+
+    /// Toggle distance-estimation rendering. When enabled,
+    /// `update_image` shades each pixel by its distance to the set's
+    /// boundary instead of by escape-time color, which keeps thin
+    /// filaments crisp regardless of zoom.
+    pub fn set_distance_mode(&mut self, enabled: bool) {
+        self.distance_mode = enabled;
+    }
+
+    /// Switch to perturbation-theory deep-zoom rendering, computing a
+    /// high-precision reference orbit at `(decimal_x, decimal_y)`.
+    ///
+    /// Needed once the zoom passes roughly 1e-15, where `f64` pixel
+    /// coordinates alone no longer have enough precision to resolve the
+    /// image; the decimal strings let the reference orbit hold as many
+    /// digits as the zoom level requires.
+    ///
+    /// The center is rejected, leaving any previous rendering mode in
+    /// place, if its reference orbit escapes before `max_iter`: deep-zoom
+    /// targets are usually chosen on or near the set's boundary, but a
+    /// reference that escapes quickly can't stand in for pixels beyond
+    /// that point, so every pixel still iterating past it would be left
+    /// with only a lower bound on its escape iteration.
+    ///
+    /// * `decimal_x`: Decimal-string x-coordinate of the new center.
+    /// * `decimal_y`: Decimal-string y-coordinate of the new center.
+    pub fn set_center(&mut self, decimal_x: String, decimal_y: String) {
+        let max_iter = self.meta.max_iter;
+        let candidate = ReferenceOrbit::compute(&decimal_x, &decimal_y, max_iter);
+        if candidate.len() < max_iter {
+            output_js(format!(
+                "set_center({}, {}) rejected: reference orbit escaped after {} of {} iterations",
+                decimal_x, decimal_y, candidate.len(), max_iter
+            ));
+            return
+        }
+        self.reference = Some(candidate);
+    }
+
+    /// Replace the color palette with the given RGB stops, so the TS UI
+    /// can swap palettes without reconstructing the engine.
+    ///
+    /// * `colors`: Flattened `[r,g,b,r,g,b,...]` stops, at least one triple.
+    ///   Fewer than 3 bytes leaves the current palette untouched, the same
+    ///   as an unrecognized name in `set_palette_preset`.
+    pub fn set_palette(&mut self, colors: &[u8]) {
+        if colors.len() < 3 {
+            return
         }
+        self.meta.palette = colors.chunks_exact(3).map(|c| [c[0],c[1],c[2]]).collect();
+    }
+
+    /// Replace the color palette with one of the built-in presets.
+    ///
+    /// * `name`: One of `"rainbow"`, `"fire"`, `"grayscale"`, `"ocean"`.
+    ///   Unrecognized names leave the current palette untouched.
+    pub fn set_palette_preset(&mut self, name: String) {
+        let n_stops = self.meta.n_colors;
+        self.meta.palette = match name.as_str() {
+            "rainbow" => rainbow_palette(n_stops),
+            "fire" => fire_palette(n_stops),
+            "grayscale" => grayscale_palette(n_stops),
+            "ocean" => ocean_palette(n_stops),
+            _ => return,
+        };
     }
 
     /// Return the pointer to the image.
@@ -172,6 +402,17 @@ impl Mandelbrot {
         self.img.as_ptr()
     }
 
+    /// Encode the current image as PNG bytes, so the TS UI can wrap them
+    /// in a Blob and offer a "save this view" download at full resolution,
+    /// rather than being limited to what the live canvas shows.
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::new();
+        PngEncoder::new(&mut bytes)
+            .write_image(&self.img, self.meta.n.x as u32, self.meta.n.y as u32, ColorType::Rgba8)
+            .expect("encoding the image buffer as PNG should not fail");
+        bytes
+    }
+
     /// Update image and compute a new image.
     /// 
     /// The input arguments are in "relative" coordinates in range [0,1],
@@ -205,24 +446,147 @@ impl Mandelbrot {
     
     /// Count the escape iterations for all indices in the image.
     fn count_iterations(&mut self) {
-        // I want the iteration in this form, so I can use rayon.
-        for (i,v) in self.work.iter_mut().enumerate() {
-            *v = self.meta.count_iter_for_index(i);
+        if let Some(reference) = self.reference.take() {
+            self.count_iterations_perturbed(&reference);
+            self.reference = Some(reference);
+        } else {
+            // Each index is independent, so hand it off to rayon's work-stealing pool.
+            let meta = &self.meta;
+            self.work.par_iter_mut()
+                .zip(self.smooth.par_iter_mut())
+                .zip(self.distance.par_iter_mut())
+                .enumerate()
+                .for_each(|(i,((v,s),dist))| {
+                    let (iter, nu, d) = meta.count_iter_for_index(i);
+                    *v = iter;
+                    *s = nu;
+                    *dist = d;
+                });
+        }
+    }
+
+    /// Count the escape iterations using perturbation theory against
+    /// `reference`, re-rendering any glitched pixels against a second,
+    /// re-centered reference orbit.
+    fn count_iterations_perturbed(&mut self, reference: &ReferenceOrbit) {
+        let meta = &self.meta;
+        let max_iter = self.meta.max_iter;
+        let glitched: Vec<(usize, PointF64)> = self.work.par_iter_mut()
+            .zip(self.smooth.par_iter_mut())
+            .enumerate()
+            .filter_map(|(i,(v,s))| {
+                let delta_c = meta.get_delta_coord(i);
+                let (iter, is_glitched) = perturbation::count_iter_perturbed(reference, delta_c, max_iter);
+                *v = iter;
+                // Perturbation mode renders plain escape-time; no smooth
+                // fractional count is computed for it.
+                *s = 0.0;
+                if is_glitched {Some((i, delta_c))} else {None}
+            })
+            .collect();
+
+        if glitched.is_empty() {
+            return
+        }
+
+        // Re-center a second reference orbit on the average glitched
+        // pixel's offset, then re-render just those pixels against it.
+        let count = glitched.len() as f64;
+        let avg = PointF64 {
+            x: glitched.iter().map(|(_,d)| d.x).sum::<f64>()/count,
+            y: glitched.iter().map(|(_,d)| d.y).sum::<f64>()/count,
+        };
+        let second = reference.recenter(avg, max_iter);
+        if second.len() < max_iter {
+            // Re-centering landed on a spot that escapes early too; keep
+            // each pixel's first-pass iteration count (a lower bound on
+            // the true escape iteration) rather than looping indefinitely
+            // trying new centers.
+            output_js(format!(
+                "perturbation: {} pixel(s) stayed glitched after re-centering, keeping their first-pass counts",
+                glitched.len()
+            ));
+            return
+        }
+        for (i, delta_c) in &glitched {
+            let rel_delta = PointF64{x: delta_c.x - avg.x, y: delta_c.y - avg.y};
+            let (iter, _) = perturbation::count_iter_perturbed(&second, rel_delta, max_iter);
+            self.work[*i] = iter;
         }
     }
 
     /// Change representation of image from #iterations to a rgba-color.
     fn iterations_to_color(&mut self) {
+        if self.reference.is_some() {
+            if self.distance_mode {
+                // Distance estimation tracks a derivative that perturbation
+                // mode doesn't compute; fall back to escape-time colors
+                // rather than rendering the stale/zero `distance` buffer.
+                output_js("distance estimation is not available in perturbation-theory deep-zoom mode; rendering escape-time colors instead".to_string());
+            }
+
+            // count_iterations_perturbed zeroes self.smooth (no normalized
+            // count is computed in perturbation mode), so color directly
+            // from the integer escape counts in self.work instead.
+            for (i, &w) in self.work.iter().enumerate() {
+                let i4 = i << 2;
+                let idx = w.min(self.iterations.len()-1);
+                let color = self.meta.color_at(self.iterations[idx] as f64);
+
+                self.img[i4] = color[0];
+                self.img[i4+1] = color[1];
+                self.img[i4+2] = color[2];
+                self.img[i4+3] = 255;
+            }
+            return
+        }
+
+        if self.distance_mode {
+            self.distance_to_color();
+            return
+        }
+
+        for (i,nu) in self.smooth.iter().enumerate() {
+            let i4 = i << 2;
 
-        for (i,w) in self.work.iter().enumerate() {
+            // Interpolate between this normalized count's bin and the
+            // next one, using its fractional part, so the color ramp is
+            // continuous instead of banded. `nu`'s integer part trails
+            // the integer escape count `self.work[i]` by a continuously-varying
+            // offset, so it must be its own histogram index -- indexing
+            // by `self.work[i]` instead would blend across the wrong pair of bins.
+            let floor_nu = nu.floor();
+            let idx = (floor_nu as usize).min(self.iterations.len()-1);
+            let next_idx = (idx+1).min(self.iterations.len()-1);
+            let bin = self.iterations[idx];
+            let next_bin = self.iterations[next_idx];
+            let weight = bin as f64 + (nu-floor_nu)*(next_bin as f64 - bin as f64);
+            let color = self.meta.color_at(weight);
+
+            self.img[i4] = color[0];
+            self.img[i4+1] = color[1];
+            self.img[i4+2] = color[2];
+            self.img[i4+3] = 255;
+        }
+    }
+
+    /// Shade each pixel by its distance estimate instead of by escape-time
+    /// color: points near the set's boundary (small `dist` relative to
+    /// the pixel size `d.x`) render dark, everything else renders light.
+    /// This keeps thin boundary filaments crisp at any zoom.
+    fn distance_to_color(&mut self) {
+        let pixel_size = self.meta.d.x;
+        for (i,dist) in self.distance.iter().enumerate() {
             let i4 = i << 2;
-            self.img[i4] = ((self.meta.red as usize*self.iterations[*w])/self.meta.n_colors) as u8;
-            self.img[i4+1] = ((self.meta.green as usize*self.iterations[*w])/self.meta.n_colors) as u8;
-            self.img[i4+2] = ((self.meta.blue as usize*self.iterations[*w])/self.meta.n_colors) as u8;
+            let shade = (dist/pixel_size).clamp(0.0, 1.0);
+            let value = (shade*255.0) as u8;
+            self.img[i4] = value;
+            self.img[i4+1] = value;
+            self.img[i4+2] = value;
             self.img[i4+3] = 255;
         }
     }
-    
+
     /// Count the frequency (or occurance) of each escape iteration.
     fn iteration_frequency(&mut self) {
 
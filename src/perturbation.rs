@@ -0,0 +1,152 @@
+/// Perturbation-theory deep-zoom rendering.
+///
+/// Ordinary `f64` escape-time collapses once the zoom factor passes
+/// roughly 10^-15, because a pixel's coordinate no longer has enough
+/// mantissa bits left to distinguish it from its neighbors. This module
+/// instead tracks one arbitrary-precision "reference orbit" at the
+/// image center, and lets every pixel iterate a much smaller, entirely
+/// `f64`-representable *delta* away from that reference.
+///
+/// The reference orbit itself is computed with `astro-float`, a pure-Rust
+/// arbitrary-precision float library. This only ever runs compiled to
+/// `wasm32-unknown-unknown`, which rules out crates like `rug` that shell
+/// out to a native GMP/MPFR build.
+use astro_float::{BigFloat, Consts, Radix, RoundingMode};
+
+use crate::PointF64;
+
+/// Precision (in bits) used for the reference orbit's arbitrary-precision
+/// arithmetic. 256 bits comfortably covers zooms well past 1e-30.
+const REFERENCE_PRECISION: usize = 256;
+
+/// Rounding mode used throughout the reference orbit's arithmetic.
+const RM: RoundingMode = RoundingMode::ToEven;
+
+/// Glitch threshold from Pauldelbrot: a pixel's delta has lost precision
+/// once `|Z_n + delta_n|` drops below this fraction of `|Z_n|`.
+const GLITCH_RATIO: f64 = 1e-3;
+
+/// A high-precision orbit `Z_0, Z_1, ..., Z_{max_iter-1}` computed for some
+/// center `c`, that every pixel's perturbed delta is iterated against.
+///
+/// `orbit.len()` is `max_iter` unless the reference itself escaped first,
+/// in which case it's shorter -- callers must treat running out of orbit
+/// as "no longer know what happens past here", not as "never escapes".
+pub struct ReferenceOrbit {
+    /// The center this orbit was computed for, kept at full precision so
+    /// a glitched re-render can shift it by an f64 delta and recompute.
+    cx: BigFloat,
+    cy: BigFloat,
+
+    /// Z_n, downcast to f64 pairs once per step. Full precision is only
+    /// needed to *compute* the orbit; each per-pixel delta recurrence
+    /// only ever needs Z_n to f64 accuracy.
+    orbit: Vec<PointF64>,
+}
+
+impl ReferenceOrbit {
+    /// Compute the reference orbit at `(center_x, center_y)`, given as
+    /// decimal strings so callers can supply more digits than `f64` can
+    /// hold, for up to `max_iter` iterations or until it escapes.
+    pub fn compute(center_x: &str, center_y: &str, max_iter: usize) -> ReferenceOrbit {
+        let mut cc = Consts::new().expect("failed to initialize astro-float constants cache");
+        let cx = BigFloat::parse(center_x, Radix::Dec, REFERENCE_PRECISION, RM, &mut cc);
+        let cy = BigFloat::parse(center_y, Radix::Dec, REFERENCE_PRECISION, RM, &mut cc);
+        ReferenceOrbit::from_center(cx, cy, max_iter)
+    }
+
+    /// Compute a second reference orbit, re-centered by `delta_c` (an
+    /// f64 offset in image coordinates) from this orbit's center. Used
+    /// to re-render pixels that glitched against this orbit.
+    pub fn recenter(&self, delta_c: PointF64, max_iter: usize) -> ReferenceOrbit {
+        let delta_cx = BigFloat::from_f64(delta_c.x, REFERENCE_PRECISION);
+        let delta_cy = BigFloat::from_f64(delta_c.y, REFERENCE_PRECISION);
+        let cx = self.cx.add(&delta_cx, REFERENCE_PRECISION, RM);
+        let cy = self.cy.add(&delta_cy, REFERENCE_PRECISION, RM);
+        ReferenceOrbit::from_center(cx, cy, max_iter)
+    }
+
+    fn from_center(cx: BigFloat, cy: BigFloat, max_iter: usize) -> ReferenceOrbit {
+        let mut zx = BigFloat::from_f64(0.0, REFERENCE_PRECISION);
+        let mut zy = BigFloat::from_f64(0.0, REFERENCE_PRECISION);
+        let two = BigFloat::from_f64(2.0, REFERENCE_PRECISION);
+        let mut orbit = Vec::with_capacity(max_iter);
+
+        for _ in 0..max_iter {
+            let (zx_f64, zy_f64) = (to_f64(&zx), to_f64(&zy));
+            orbit.push(PointF64{x: zx_f64, y: zy_f64});
+
+            if zx_f64*zx_f64 + zy_f64*zy_f64 >= 4.0 {
+                break
+            }
+
+            // Z_{n+1} = Z_n^2 + c, done at full reference precision.
+            let zx2 = zx.mul(&zx, REFERENCE_PRECISION, RM);
+            let zy2 = zy.mul(&zy, REFERENCE_PRECISION, RM);
+            let zxy = zx.mul(&zy, REFERENCE_PRECISION, RM);
+            let zx_new = zx2.sub(&zy2, REFERENCE_PRECISION, RM).add(&cx, REFERENCE_PRECISION, RM);
+            let zy_new = zxy.mul(&two, REFERENCE_PRECISION, RM).add(&cy, REFERENCE_PRECISION, RM);
+            zx = zx_new;
+            zy = zy_new;
+        }
+        ReferenceOrbit{cx, cy, orbit}
+    }
+
+    /// Number of iterations this reference orbit actually covers. Shorter
+    /// than `max_iter` means the reference escaped before completing it.
+    pub fn len(&self) -> usize {
+        self.orbit.len()
+    }
+}
+
+/// Downcast a `BigFloat` to `f64` by formatting it to decimal and parsing
+/// that back with the standard library -- `astro-float`'s public `BigFloat`
+/// has no direct `to_f64`, but it does implement decimal `Display`.
+fn to_f64(x: &BigFloat) -> f64 {
+    format!("{}", x).parse().unwrap_or(0.0)
+}
+
+/// Iterate the perturbed recurrence for one pixel's `delta_c` (its f64
+/// offset from the reference orbit's center) against `reference`, for up
+/// to `max_iter` iterations.
+///
+/// Returns `(iter, glitched)`: the escape iteration (0 if the pixel
+/// never escapes within `max_iter`) and whether a glitch was detected.
+/// A glitched pixel should be re-rendered against a second reference
+/// orbit -- either because its delta lost precision (Pauldelbrot's
+/// criterion), or because `reference` itself ran out before `max_iter`
+/// (it escaped too early to validate this pixel that far), in which case
+/// `iter` is only a lower bound on the true escape iteration.
+pub fn count_iter_perturbed(reference: &ReferenceOrbit, delta_c: PointF64, max_iter: usize) -> (usize, bool) {
+    let mut dx = 0.0;
+    let mut dy = 0.0;
+
+    for iter in 0..max_iter {
+        let z = match reference.orbit.get(iter) {
+            Some(z) => z,
+            None => return (iter, true),
+        };
+
+        let zx = z.x + dx;
+        let zy = z.y + dy;
+        let mag2 = zx*zx + zy*zy;
+
+        if mag2 >= 4.0 {
+            return (iter, false)
+        }
+
+        // Pauldelbrot glitch detection: delta has swamped the signal,
+        // the reference orbit can no longer stand in for this pixel.
+        let z_mag2 = z.x*z.x + z.y*z.y;
+        if mag2 < GLITCH_RATIO*GLITCH_RATIO*z_mag2 {
+            return (iter, true)
+        }
+
+        // delta_{n+1} = 2*Z_n*delta_n + delta_n^2 + delta_c
+        let dx_new = 2.0*(z.x*dx - z.y*dy) + (dx*dx - dy*dy) + delta_c.x;
+        let dy_new = 2.0*(z.x*dy + z.y*dx) + 2.0*dx*dy + delta_c.y;
+        dx = dx_new;
+        dy = dy_new;
+    }
+    (0, false)
+}